@@ -1,11 +1,12 @@
 use anyhow::Result;
 use clap::{Args, Parser, Subcommand};
 use itertools::Itertools;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_xml_rs::from_reader;
 use std::fs::{self, File};
 use std::io::{self, BufReader};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 fn default_build_type() -> String {
     "ros.catkin".to_string()
@@ -28,6 +29,43 @@ struct Package {
     name: String,
     #[serde(default = "default_export")]
     export: Export,
+
+    // package.xml spells out dependencies across several differently-named
+    // tags depending on when they're needed; we fold them all into a single
+    // `dependencies` list once parsing is done.
+    #[serde(default, rename = "depend")]
+    depend: Vec<String>,
+    #[serde(default, rename = "build_depend")]
+    build_depend: Vec<String>,
+    #[serde(default, rename = "buildtool_depend")]
+    buildtool_depend: Vec<String>,
+    #[serde(default, rename = "build_export_depend")]
+    build_export_depend: Vec<String>,
+    #[serde(default, rename = "exec_depend")]
+    exec_depend: Vec<String>,
+    #[serde(default, rename = "run_depend")]
+    run_depend: Vec<String>,
+    #[serde(default, rename = "test_depend")]
+    test_depend: Vec<String>,
+
+    #[serde(skip)]
+    dependencies: Vec<String>,
+}
+
+impl Package {
+    fn collect_dependencies(&mut self) {
+        self.dependencies = self
+            .depend
+            .iter()
+            .chain(&self.build_depend)
+            .chain(&self.buildtool_depend)
+            .chain(&self.build_export_depend)
+            .chain(&self.exec_depend)
+            .chain(&self.run_depend)
+            .chain(&self.test_depend)
+            .cloned()
+            .collect();
+    }
 }
 
 struct Entry {
@@ -54,6 +92,24 @@ impl Entry {
             self.print();
         }
     }
+
+    fn to_json(&self) -> PackageJson {
+        PackageJson {
+            name: &self.pkg.name,
+            path: self.path.to_string_lossy(),
+            build_type: &self.pkg.export.build_type,
+            dependencies: &self.pkg.dependencies,
+        }
+    }
+}
+
+/// The JSON representation of an `Entry`, emitted by `cols list --format json`.
+#[derive(Serialize)]
+struct PackageJson<'a> {
+    name: &'a str,
+    path: std::borrow::Cow<'a, str>,
+    build_type: &'a str,
+    dependencies: &'a [String],
 }
 
 enum SearchOutcome {
@@ -65,8 +121,76 @@ enum SearchOutcome {
 
 static IGNORE_MARKERS: [&str; 3] = ["COLCON_IGNORE", "CATKIN_IGNORE", "AMENT_IGNORE"];
 
+/// The subset of a parsed `Package` that's worth caching, keyed in
+/// `ParseCache` by the `package.xml` path it came from.
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedPackage {
+    mtime_secs: u64,
+    name: String,
+    build_type: String,
+    dependencies: Vec<String>,
+}
+
+impl CachedPackage {
+    fn from_package(pkg: &Package, mtime_secs: u64) -> Self {
+        CachedPackage {
+            mtime_secs,
+            name: pkg.name.clone(),
+            build_type: pkg.export.build_type.clone(),
+            dependencies: pkg.dependencies.clone(),
+        }
+    }
+
+    fn into_package(self) -> Package {
+        Package {
+            name: self.name,
+            export: Export {
+                build_type: self.build_type,
+            },
+            depend: Vec::new(),
+            build_depend: Vec::new(),
+            buildtool_depend: Vec::new(),
+            build_export_depend: Vec::new(),
+            exec_depend: Vec::new(),
+            run_depend: Vec::new(),
+            test_depend: Vec::new(),
+            dependencies: self.dependencies,
+        }
+    }
+}
+
+/// Maps an absolute `package.xml` path to its last-seen mtime and parsed
+/// fields, so repeated invocations over an unchanged workspace can skip
+/// re-parsing XML entirely.
+type ParseCache = std::collections::HashMap<String, CachedPackage>;
+
+fn load_cache(cache_dir: &Path) -> ParseCache {
+    fs::read_to_string(cache_dir.join("package_cache.json"))
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// Prunes entries for files that no longer exist, then writes the cache
+/// back out as JSON under `cache_dir`.
+fn save_cache(cache_dir: &Path, mut cache: ParseCache) -> io::Result<()> {
+    cache.retain(|xml_path, _| Path::new(xml_path).exists());
+    fs::create_dir_all(cache_dir)?;
+    let contents = serde_json::to_string_pretty(&cache).unwrap_or_default();
+    fs::write(cache_dir.join("package_cache.json"), contents)
+}
+
+fn mtime_secs(metadata: &fs::Metadata) -> Option<u64> {
+    metadata
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
 // TODO: follow symlinks?
-fn check_path(dir: &Path) -> SearchOutcome {
+fn check_path(dir: &Path, cache: Option<&Mutex<ParseCache>>) -> SearchOutcome {
     use SearchOutcome::*;
     if !dir.is_dir() {
         return SearchOutcome::IsFile {};
@@ -89,7 +213,7 @@ fn check_path(dir: &Path) -> SearchOutcome {
 
     let pkg_xml = dir.join("package.xml");
     if pkg_xml.exists() {
-        match parse_package(&pkg_xml) {
+        match parse_package(&pkg_xml, cache) {
             Ok(pkg) => {
                 return Found(Entry {
                     pkg,
@@ -104,43 +228,123 @@ fn check_path(dir: &Path) -> SearchOutcome {
     Recurse {}
 }
 
-fn parse_package(xml_file: &PathBuf) -> Result<Package> {
+fn parse_package(xml_file: &PathBuf, cache: Option<&Mutex<ParseCache>>) -> Result<Package> {
+    let metadata = fs::metadata(xml_file)?;
+    let key = fs::canonicalize(xml_file)
+        .unwrap_or_else(|_| xml_file.clone())
+        .to_string_lossy()
+        .to_string();
+
+    if let (Some(cache), Some(mtime_secs)) = (cache, mtime_secs(&metadata)) {
+        if let Some(cached) = cache.lock().unwrap().get(&key) {
+            if cached.mtime_secs == mtime_secs {
+                return Ok(cached.clone().into_package());
+            }
+        }
+    }
+
     let f = File::open(xml_file)?;
     let reader = BufReader::new(f);
-    let p: Package = from_reader(reader)?;
+    let mut p: Package = from_reader(reader)?;
+    p.collect_dependencies();
+
+    if let (Some(cache), Some(mtime_secs)) = (cache, mtime_secs(&metadata)) {
+        cache
+            .lock()
+            .unwrap()
+            .insert(key, CachedPackage::from_package(&p, mtime_secs));
+    }
+
     Ok(p)
 }
 
-fn find_packages(dir: &Path, results: &mut Vec<Entry>, recurse: bool) -> io::Result<()> {
+/// Walks `dir` in parallel using the `ignore` crate, which also honors any
+/// `.gitignore`/`.ignore` files it finds (unless `respect_gitignore` is
+/// false). Descent into a directory stops as soon as it's recognized as
+/// ignored or as a package, matching `check_path`'s semantics; this keeps
+/// the walk out of build/install/`.git` output trees on large workspaces.
+fn find_packages(
+    dir: &Path,
+    results: &mut Vec<Entry>,
+    recurse: bool,
+    respect_gitignore: bool,
+    cache: Option<&Mutex<ParseCache>>,
+) -> io::Result<()> {
     if !dir.is_dir() {
         return Ok(());
     }
-    use SearchOutcome::*;
-    for entry in (fs::read_dir(dir)?).flatten() {
-        let check_outcome = check_path(&entry.path());
-        match check_outcome {
-            Found(entry) => {
-                results.push(entry);
-            }
-            Recurse if recurse => {
-                find_packages(&entry.path(), results, recurse)?;
-            }
-            _ => {}
-        }
-    }
+
+    let found = Mutex::new(Vec::<Entry>::new());
+    ignore::WalkBuilder::new(dir)
+        .hidden(true)
+        .git_ignore(respect_gitignore)
+        .git_global(respect_gitignore)
+        .git_exclude(respect_gitignore)
+        .ignore(respect_gitignore)
+        .require_git(false)
+        .max_depth(if recurse { None } else { Some(1) })
+        .build_parallel()
+        .run(|| {
+            let found = &found;
+            Box::new(move |entry| {
+                use SearchOutcome::*;
+                let entry = match entry {
+                    Ok(entry) if entry.depth() > 0 => entry,
+                    _ => return ignore::WalkState::Continue,
+                };
+                match check_path(entry.path(), cache) {
+                    Found(pkg_entry) => {
+                        found.lock().unwrap().push(pkg_entry);
+                        ignore::WalkState::Skip
+                    }
+                    Ignored => ignore::WalkState::Skip,
+                    IsFile | Recurse => ignore::WalkState::Continue,
+                }
+            })
+        });
+
+    results.extend(found.into_inner().unwrap());
     Ok(())
 }
 
-fn find_wrapper(dir: &Path, results: &mut Vec<Entry>, recurse: bool) -> io::Result<()> {
-    if let SearchOutcome::Found(entry) = check_path(dir) {
+fn find_wrapper(
+    dir: &Path,
+    results: &mut Vec<Entry>,
+    recurse: bool,
+    respect_gitignore: bool,
+    cache: Option<&Mutex<ParseCache>>,
+) -> io::Result<()> {
+    if let SearchOutcome::Found(entry) = check_path(dir, cache) {
         results.push(entry);
     }
-    find_packages(dir, results, recurse)?;
+    find_packages(dir, results, recurse, respect_gitignore, cache)?;
     Ok(())
 }
 
+fn is_glob_pattern(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// Expands any path containing shell glob metacharacters into the set of
+/// directory entries it matches, leaving plain paths untouched. This lets
+/// `--paths`/`--base-paths` behave the same whether or not the invoking
+/// shell already expanded the glob.
+fn expand_globs(raw: &[std::path::PathBuf]) -> Vec<std::path::PathBuf> {
+    raw.iter()
+        .flat_map(|p| -> Vec<std::path::PathBuf> {
+            if !is_glob_pattern(p) {
+                return vec![p.clone()];
+            }
+            match glob::glob(&p.to_string_lossy()) {
+                Ok(paths) => paths.flatten().collect(),
+                Err(_e) => vec![p.clone()],
+            }
+        })
+        .collect()
+}
+
 fn preprocess_paths(raw: &[std::path::PathBuf]) -> Vec<std::path::PathBuf> {
-    return raw
+    return expand_globs(raw)
         .iter()
         .dedup()
         .map(|p| p.canonicalize().unwrap_or(p.clone()))
@@ -151,25 +355,89 @@ fn preprocess_paths(raw: &[std::path::PathBuf]) -> Vec<std::path::PathBuf> {
 fn collect_packages_from_args(
     raw_paths: &[std::path::PathBuf],
     base_paths: &[std::path::PathBuf],
+    respect_gitignore: bool,
+    cache: Option<&Mutex<ParseCache>>,
 ) -> io::Result<Vec<Entry>> {
     let mut res = Vec::<Entry>::new();
 
     let unique_paths = preprocess_paths(raw_paths);
     for to_check in &unique_paths {
-        find_wrapper(to_check, &mut res, false)?;
+        find_wrapper(to_check, &mut res, false, respect_gitignore, cache)?;
     }
 
     if unique_paths.is_empty() && base_paths.is_empty() {
-        find_wrapper(Path::new("."), &mut res, true)?;
+        find_wrapper(Path::new("."), &mut res, true, respect_gitignore, cache)?;
     } else {
         preprocess_paths(base_paths)
             .into_iter()
-            .map(|p| find_wrapper(&p, &mut res, true))
+            .map(|p| find_wrapper(&p, &mut res, true, respect_gitignore, cache))
             .collect::<io::Result<Vec<_>>>()?;
     }
     Ok(res)
 }
 
+/// Orders `entries` so that every package appears after all of its
+/// dependencies that are also present in `entries`, using a Kahn sort that
+/// always emits the lexicographically-smallest ready package. This matches
+/// colcon's own topological ordering, including its tie-breaking.
+///
+/// If a dependency cycle prevents some packages from ever becoming ready,
+/// those packages are reported on stderr and appended at the end in name
+/// order rather than being dropped from the output.
+fn topological_order(entries: Vec<Entry>) -> Vec<Entry> {
+    use std::collections::{BTreeSet, HashMap};
+
+    let index_by_name: HashMap<&str, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, e)| (e.pkg.name.as_str(), i))
+        .collect();
+
+    let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); entries.len()];
+    let mut in_degree = vec![0usize; entries.len()];
+    for (i, entry) in entries.iter().enumerate() {
+        for dep in &entry.pkg.dependencies {
+            if let Some(&dep_idx) = index_by_name.get(dep.as_str()) {
+                dependents[dep_idx].push(i);
+                in_degree[i] += 1;
+            }
+        }
+    }
+
+    let mut ready: BTreeSet<(&str, usize)> = entries
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| in_degree[*i] == 0)
+        .map(|(i, e)| (e.pkg.name.as_str(), i))
+        .collect();
+
+    let mut order = Vec::with_capacity(entries.len());
+    while let Some(&(name, i)) = ready.iter().next() {
+        ready.remove(&(name, i));
+        order.push(i);
+        for &dependent in &dependents[i] {
+            in_degree[dependent] -= 1;
+            if in_degree[dependent] == 0 {
+                ready.insert((entries[dependent].pkg.name.as_str(), dependent));
+            }
+        }
+    }
+
+    if order.len() < entries.len() {
+        let emitted: std::collections::HashSet<usize> = order.iter().copied().collect();
+        let mut remaining: Vec<usize> = (0..entries.len()).filter(|i| !emitted.contains(i)).collect();
+        remaining.sort_unstable_by(|&a, &b| entries[a].pkg.name.cmp(&entries[b].pkg.name));
+        eprintln!(
+            "[WARNING] Dependency cycle detected, emitting these packages last: {}",
+            remaining.iter().map(|&i| entries[i].pkg.name.as_str()).join(", ")
+        );
+        order.extend(remaining);
+    }
+
+    let mut entries: Vec<Option<Entry>> = entries.into_iter().map(Some).collect();
+    order.into_iter().map(|i| entries[i].take().unwrap()).collect()
+}
+
 macro_rules! print_unless_quiet {
     ($i:ident, $($arg:tt)*) => {
         if !$i {
@@ -234,12 +502,24 @@ struct MainArgs {
     command: Commands,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    /// The usual `name\tpath\t(build_type)` (or single-column) lines
+    Text,
+    /// A JSON array of objects, one per package
+    Json,
+}
+
 #[derive(Args)]
 struct ListArgs {
-    /// Not implemented
+    /// Order output so every package comes after its dependencies
     #[arg(short = 't', long, default_value_t = false)]
     topological_order: bool,
 
+    /// Output format
+    #[arg(long, value_enum, default_value = "text")]
+    format: OutputFormat,
+
     /// Output only the name of each package but not the path
     #[arg(
         short = 'n',
@@ -263,9 +543,17 @@ struct ListArgs {
     base_paths: Vec<std::path::PathBuf>,
 
     /// The paths to check for a package. Use shell wildcards (e.g. `src/*`) to select all direct subdirectories
-    /// TODO: we don't do globs yet
     #[arg(long, num_args = 0..)]
     paths: Vec<std::path::PathBuf>,
+
+    /// Don't skip directories matched by .gitignore/.ignore files while crawling
+    #[arg(long, default_value_t = false)]
+    no_gitignore: bool,
+
+    /// Cache parsed package.xml files here, keyed by path and modification time, to
+    /// speed up repeated invocations over an unchanged workspace. Off by default.
+    #[arg(long)]
+    cache_dir: Option<std::path::PathBuf>,
 }
 
 #[derive(Args)]
@@ -275,10 +563,18 @@ struct SymlinkArgs {
     base_paths: Vec<std::path::PathBuf>,
 
     /// The paths to check for a package. Use shell wildcards (e.g. `src/*`) to select all direct subdirectories
-    /// TODO: we don't do globs yet
     #[arg(long, num_args = 0..)]
     paths: Vec<std::path::PathBuf>,
 
+    /// Don't skip directories matched by .gitignore/.ignore files while crawling
+    #[arg(long, default_value_t = false)]
+    no_gitignore: bool,
+
+    /// Cache parsed package.xml files here, keyed by path and modification time, to
+    /// speed up repeated invocations over an unchanged workspace. Off by default.
+    #[arg(long)]
+    cache_dir: Option<std::path::PathBuf>,
+
     /// The base path for all build directories
     #[arg(long)]
     build_base: std::path::PathBuf,
@@ -311,22 +607,54 @@ fn rel_to_cwd(build_base: PathBuf) -> PathBuf {
     }
 }
 
-fn main() -> io::Result<()> {
+fn main() -> Result<()> {
     let args = MainArgs::parse();
     match &args.command {
         Commands::List(list_args) => {
-            let res = collect_packages_from_args(&list_args.paths, &list_args.base_paths)?;
+            let cache = list_args.cache_dir.as_ref().map(|d| Mutex::new(load_cache(d)));
+            let res = collect_packages_from_args(
+                &list_args.paths,
+                &list_args.base_paths,
+                !list_args.no_gitignore,
+                cache.as_ref(),
+            )?;
+            if let (Some(cache_dir), Some(cache)) = (&list_args.cache_dir, cache) {
+                let _ = save_cache(cache_dir, cache.into_inner().unwrap());
+            }
 
-            for e in res
-                .iter()
-                .sorted_unstable_by_key(|e| (&e.pkg.name, &e.path))
+            let mut res: Vec<Entry> = res
+                .into_iter()
+                .sorted_unstable_by_key(|e| (e.pkg.name.clone(), e.path.clone()))
                 .dedup_by(|a, b| a.pkg.name == b.pkg.name && a.path == b.path)
-            {
-                e.print_from_opts(list_args);
+                .collect();
+
+            if list_args.topological_order {
+                res = topological_order(res);
+            }
+
+            if list_args.format == OutputFormat::Json {
+                let payload: Vec<PackageJson> = res.iter().map(Entry::to_json).collect();
+                println!("{}", serde_json::to_string_pretty(&payload)?);
+            } else {
+                for e in &res {
+                    e.print_from_opts(list_args);
+                }
             }
         }
         Commands::Symlink(symlink_args) => {
-            let res = collect_packages_from_args(&symlink_args.paths, &symlink_args.base_paths)?;
+            let cache = symlink_args
+                .cache_dir
+                .as_ref()
+                .map(|d| Mutex::new(load_cache(d)));
+            let res = collect_packages_from_args(
+                &symlink_args.paths,
+                &symlink_args.base_paths,
+                !symlink_args.no_gitignore,
+                cache.as_ref(),
+            )?;
+            if let (Some(cache_dir), Some(cache)) = (&symlink_args.cache_dir, cache) {
+                let _ = save_cache(cache_dir, cache.into_inner().unwrap());
+            }
             let fixed_build = symlink_args
                 .build_base
                 .canonicalize()